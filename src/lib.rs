@@ -6,12 +6,14 @@ use tokio::task::JoinHandle;
 use tracing_core::field::Visit;
 use tracing_core::span::{Attributes, Id, Record};
 use tracing_core::subscriber::Subscriber;
-use tracing_core::Field;
+use tracing_core::{Event, Field, Level};
 use tracing_subscriber::layer::Context;
-use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
 
 mod model;
+pub mod sampler;
 pub mod trace_id;
+mod transport;
 mod xray_daemon;
 
 /// Add `aws.xray.trace_id` as a field to a tracing span to designate it as an
@@ -24,17 +26,56 @@ pub const TRACE_ID_FIELD: &str = "aws.xray.trace_id";
 /// `aws.xray.annotations.`.
 pub const ANNOTATION_PREFIX: &str = "aws.xray.annotations.";
 
+/// Prefix span fields that make up a segment's [`http` block] with
+/// `aws.xray.http.`. Recognized sub-keys are `request.method`,
+/// `request.url`, `request.client_ip`, `request.user_agent`,
+/// `response.status`, and `response.content_length`; a `response.status`
+/// in the 4xx/5xx range also sets the segment's `error`/`fault` flag.
+///
+/// [`http` block]: https://docs.aws.amazon.com/xray/latest/devguide/xray-api-segmentdocuments.html#api-segmentdocuments-http
+pub const HTTP_PREFIX: &str = "aws.xray.http.";
+
+/// Prefix span fields that make up a subsegment's [`sql` block] with
+/// `aws.xray.sql.`. Recognized sub-keys are `url`, `sanitized_query`,
+/// `database_type`, `database_version`, `driver_version`, `user`, and
+/// `preparation`. A subsegment carrying an `sql` block (or an
+/// `aws.xray.http.request.url`) automatically gets `namespace = "remote"`,
+/// rendering it as a downstream node in the X-Ray service map.
+///
+/// [`sql` block]: https://docs.aws.amazon.com/xray/latest/devguide/xray-api-segmentdocuments.html#api-segmentdocuments-sql
+pub const SQL_PREFIX: &str = "aws.xray.sql.";
+
+/// Set this field on an `ERROR`/`WARN` event to mark the exception it
+/// records as a [throttling] error: the segment's `throttle` flag is set,
+/// in addition to the `fault`/`error` flag that the event's level sets.
+///
+/// [throttling]: https://docs.aws.amazon.com/xray/latest/devguide/xray-api-segmentdocuments.html
+pub const THROTTLE_FIELD: &str = "aws.xray.throttle";
+
+/// Set this field (to `"1"`, `"0"`, or `"?"`, matching the `Sampled` value
+/// of an [AWS X-Ray tracing header]) on the span that carries
+/// [`TRACE_ID_FIELD`] to convey an inbound sampling decision. If the field
+/// is omitted, or set to `"?"`, the [`Layer`]'s [`Sampler`][sampler::Sampler]
+/// makes the call instead.
+///
+/// [AWS X-Ray tracing header]: https://docs.aws.amazon.com/xray/latest/devguide/xray-concepts.html#xray-concepts-tracingheader
+pub const SAMPLED_FIELD: &str = "aws.xray.sampled";
+
 /// A [tracing_subscriber] [`Layer`][tracing_subscriber::layer::Layer] that
-/// emits traces to an [AWS X-Ray daemon].
+/// emits traces to [AWS X-Ray].
 ///
-/// This layer assumes the X-Ray daemon is running locally, and listening on
-/// port 2000.
+/// By default, this layer ships segment documents to an [X-Ray daemon] at
+/// the address in `AWS_XRAY_DAEMON_ADDRESS`, or running locally on UDP
+/// port 2000 if that's unset. Use [`Layer::builder`] to ship to a
+/// different daemon address, or straight to the X-Ray service.
 ///
-/// [AWS X-Ray daemon]: https://docs.aws.amazon.com/xray/latest/devguide/xray-daemon.html
+/// [AWS X-Ray]: https://docs.aws.amazon.com/xray/latest/devguide/aws-xray.html
+/// [X-Ray daemon]: https://docs.aws.amazon.com/xray/latest/devguide/xray-daemon.html
 pub struct Layer {
     handle: JoinHandle<io::Result<()>>,
     sender: mpsc::Sender<model::Segment>,
     service_name: String,
+    sampler: sampler::Sampler,
 }
 
 impl std::ops::Drop for Layer {
@@ -44,30 +85,151 @@ impl std::ops::Drop for Layer {
 }
 
 impl Layer {
-    /// Constructs a new [`Layer`].
+    /// Constructs a new [`Layer`], shipping segment documents to a local
+    /// X-Ray daemon. Equivalent to `Layer::builder(service_name).build()`.
     ///
     /// The given `service_name` is used as the `name` for segment documentes
     /// emitted by this layer.
     pub async fn new(service_name: impl ToString) -> io::Result<Self> {
-        let connection = xray_daemon::DaemonClient::default().connect().await?;
+        Self::builder(service_name).build().await
+    }
+
+    /// Start configuring a [`Layer`] with a non-default
+    /// [`Sampler`][sampler::Sampler], or
+    /// with a transport other than the local X-Ray daemon (see
+    /// [`Builder::api`]).
+    pub fn builder(service_name: impl ToString) -> Builder {
+        Builder::new(service_name)
+    }
+
+    /// Use a custom [`Sampler`][sampler::Sampler] to decide which new root
+    /// traces to sample, in place of the default rule (a reservoir of 1
+    /// trace/second, plus a 5% fixed rate).
+    pub fn with_sampler(mut self, sampler: sampler::Sampler) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Emit a given [`model::Segment`].
+    fn send(&self, segment: &model::Segment) {
+        let _ = self.sender.try_send(segment.to_owned());
+    }
+
+    /// Decide (for a new root trace) or inherit (for a subsegment) this
+    /// span's X-Ray sampling decision.
+    fn sampled<S>(&self, span: &SpanRef<'_, S>, attr: &Attributes<'_>, kind: &model::Kind) -> bool
+    where
+        S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+    {
+        if let model::Kind::Subsegment = kind {
+            // subsegments inherit the root trace's sampling decision
+            return span
+                .scope()
+                .skip(1)
+                .find_map(|span| span.extensions().get::<Sampled>().map(|sampled| sampled.0))
+                .unwrap_or(true);
+        }
+
+        // a new root trace: respect an explicit inbound decision, otherwise
+        // consult the local sampler
+        let mut visitor = SampledVisitor { decision: None };
+        attr.record(&mut visitor);
+        match visitor.decision {
+            Some(trace_id::SamplingDecision::Sampled) => true,
+            Some(trace_id::SamplingDecision::NotSampled) => false,
+            _ => self.sampler.sample(),
+        }
+    }
+}
+
+/// How a [`Builder`]-configured [`Layer`] ships its segment documents.
+enum TransportConfig {
+    /// A local X-Ray daemon, listening at the given address (defaulting to
+    /// [`AWS_XRAY_DAEMON_ADDRESS`], or `127.0.0.1:2000` if that's unset).
+    ///
+    /// [`AWS_XRAY_DAEMON_ADDRESS`]: https://docs.aws.amazon.com/xray/latest/devguide/xray-daemon.html
+    Daemon { address: Option<String> },
+    /// The X-Ray service itself, via `PutTraceSegments`.
+    Api,
+}
+
+/// Builds a [`Layer`], configuring its [`Sampler`][sampler::Sampler] and
+/// the transport it ships segment documents over.
+pub struct Builder {
+    service_name: String,
+    sampler: sampler::Sampler,
+    transport: TransportConfig,
+}
+
+impl Builder {
+    fn new(service_name: impl ToString) -> Self {
+        Self {
+            service_name: service_name.to_string(),
+            sampler: sampler::Sampler::default(),
+            transport: TransportConfig::Daemon { address: None },
+        }
+    }
+
+    /// Use a custom [`Sampler`][sampler::Sampler] to decide which new root
+    /// traces to sample, in place of the default rule.
+    pub fn sampler(mut self, sampler: sampler::Sampler) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Ship segment documents to the X-Ray daemon listening at `address`
+    /// (a `host:port`), instead of the address resolved from
+    /// `AWS_XRAY_DAEMON_ADDRESS`/the default `127.0.0.1:2000`.
+    pub fn daemon(mut self, address: impl ToString) -> Self {
+        self.transport = TransportConfig::Daemon {
+            address: Some(address.to_string()),
+        };
+        self
+    }
+
+    /// Ship segment documents straight to the X-Ray service via
+    /// `PutTraceSegments`, bypassing the local daemon. Useful wherever a
+    /// daemon isn't reachable: Lambda-free containers, CI, restricted
+    /// networks.
+    pub fn api(mut self) -> Self {
+        self.transport = TransportConfig::Api;
+        self
+    }
+
+    /// Construct the configured [`Layer`].
+    pub async fn build(self) -> io::Result<Layer> {
+        let transport = match self.transport {
+            TransportConfig::Daemon { address } => {
+                let client = match address {
+                    Some(address) => xray_daemon::DaemonClient::new(address),
+                    None => xray_daemon::DaemonClient::default(),
+                };
+                transport::Transport::Daemon(client.connect().await?)
+            }
+            TransportConfig::Api => {
+                transport::Transport::Api(transport::ApiClient::connect().await)
+            }
+        };
+
         let (sender, mut receiver) = mpsc::channel::<model::Segment>(1000);
-        Ok(Self {
+        Ok(Layer {
             handle: tokio::spawn(async move {
-                while let Some(segment) = receiver.recv().await {
-                    let message = serde_json::to_vec(&segment).unwrap();
-                    connection.send(&message[..]).await?;
+                while let Some(first) = receiver.recv().await {
+                    // ship everything that's buffered up as one batch,
+                    // rather than one segment document at a time
+                    let mut batch = vec![first];
+                    while let Ok(segment) = receiver.try_recv() {
+                        batch.push(segment);
+                    }
+                    transport.send(&batch).await?;
                 }
                 Ok(())
             }),
             sender,
-            service_name: service_name.to_string(),
+            service_name: self.service_name,
+            sampler: self.sampler,
         })
     }
-
-    /// Emit a given [`model::Segment`].
-    fn send(&self, segment: &model::Segment) {
-        let _ = self.sender.try_send(segment.to_owned());
-    }
 }
 
 /// A [visitor][Visit] that searches for fields named
@@ -87,6 +249,53 @@ impl Visit for TraceIdVisitor {
     }
 }
 
+/// Whether a trace was decided to be sampled. Recorded in a span's
+/// extensions so descendant subsegments can inherit the decision.
+struct Sampled(bool);
+
+/// A [visitor][Visit] that searches for fields named
+/// [`aws.xray.sampled`][SAMPLED_FIELD] and records their value.
+struct SampledVisitor {
+    decision: Option<trace_id::SamplingDecision>,
+}
+
+impl Visit for SampledVisitor {
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == SAMPLED_FIELD {
+            self.decision = Some(trace_id::SamplingDecision::from_str(value));
+        }
+    }
+}
+
+/// A [visitor][Visit] that extracts the `message` and
+/// [`THROTTLE_FIELD`] of an `ERROR`/`WARN` event.
+#[derive(Default)]
+struct ExceptionVisitor {
+    message: Option<String>,
+    throttle: bool,
+}
+
+impl Visit for ExceptionVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = self.message.get_or_insert_with(|| format!("{value:?}"));
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            let _ = self.message.insert(value.to_owned());
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if field.name() == THROTTLE_FIELD {
+            self.throttle = value;
+        }
+    }
+}
+
 impl<S> tracing_subscriber::layer::Layer<S> for Layer
 where
     S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
@@ -106,9 +315,24 @@ where
             false => model::Kind::Subsegment,
         };
 
-        // prepare X-Ray annotations and metadata for this span
+        // decide (or inherit) this trace's sampling decision, and record it
+        // so descendant subsegments can inherit it in turn
+        let sampled = self.sampled(&span, attr, &kind);
+        span.extensions_mut().insert(Sampled(sampled));
+        if !sampled {
+            // X-Ray discards unsampled traces entirely: don't create or
+            // emit a segment for this span.
+            return;
+        }
+
+        // prepare X-Ray annotations, metadata, and the `http`/`sql` blocks for this span
         let record = Record::new(attr.values());
-        let (mut metadata, mut annotations) = crate::model::metadata_and_annotations_from(&record);
+        let model::ParsedFields {
+            mut metadata,
+            mut annotations,
+            http,
+            sql,
+        } = model::fields_from(&record);
 
         annotations
             .fields
@@ -124,7 +348,7 @@ where
                 .add("tracing.name", attr.metadata().name());
         }
 
-        let segment = model::Segment {
+        let mut segment = model::Segment {
             name: {
                 match kind {
                     // for segments, use the logical name of the service
@@ -140,8 +364,18 @@ where
             kind,
             metadata,
             annotations,
+            namespace: None,
+            http: None,
+            sql: None,
+            error: None,
+            fault: None,
+            throttle: None,
+            cause: None,
             rest: model::Rest::InProgress(model::InProgress),
         };
+        segment.record_http(http);
+        segment.record_sql(sql);
+        segment.update_namespace();
         let _ = self.send(&segment);
         span.extensions_mut().insert(segment);
     }
@@ -151,10 +385,51 @@ where
     fn on_record(&self, id: &Id, record: &Record<'_>, ctx: Context<'_, S>) {
         let span = ctx.span(id).expect("Span not found, this is a bug");
         let mut extensions = span.extensions_mut();
-        let (metadata, annotations) = crate::model::metadata_and_annotations_from(record);
+        let model::ParsedFields {
+            metadata,
+            annotations,
+            http,
+            sql,
+        } = model::fields_from(record);
         if let Some(segment) = extensions.get_mut::<model::Segment>() {
             segment.metadata.update(metadata);
             segment.annotations.update(annotations);
+            segment.record_http(http);
+            segment.record_sql(sql);
+            segment.update_namespace();
+        }
+    }
+
+    // `ERROR`/`WARN` events are recorded as X-Ray exceptions on the nearest
+    // enclosing segment/subsegment, setting its `fault`/`error` flag.
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let fault = *event.metadata().level() == Level::ERROR;
+        let error = *event.metadata().level() == Level::WARN;
+        if !fault && !error {
+            return;
+        }
+
+        let span = match ctx.event_span(event) {
+            Some(span) => span,
+            None => return,
+        };
+
+        let mut visitor = ExceptionVisitor::default();
+        event.record(&mut visitor);
+
+        let exception = model::Exception {
+            id: model::new_exception_id(),
+            message: visitor.message,
+            kind: event.metadata().target().to_owned(),
+            stack: None,
+        };
+
+        for span in span.scope() {
+            let mut extensions = span.extensions_mut();
+            if let Some(segment) = extensions.get_mut::<model::Segment>() {
+                segment.record_exception(exception, fault, error, visitor.throttle);
+                break;
+            }
         }
     }
 
@@ -29,6 +29,20 @@ pub(crate) struct Segment {
     pub(crate) kind: Kind,
     pub(crate) metadata: Metadata,
     pub(crate) annotations: Annotations,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) http: Option<Http>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) sql: Option<Sql>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) fault: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) throttle: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cause: Option<Cause>,
     #[serde(flatten)]
     pub(crate) rest: Rest,
 }
@@ -40,6 +54,268 @@ impl Segment {
             end_time: SystemTime::now(),
         });
     }
+
+    /// Record an exception raised within this segment, setting the
+    /// corresponding `error`/`fault`/`throttle` flags. Flags are OR'd with
+    /// any already recorded, so a segment that saw both a `WARN` and an
+    /// `ERROR` event ends up with both `error` and `fault` set.
+    pub(crate) fn record_exception(
+        &mut self,
+        exception: Exception,
+        fault: bool,
+        error: bool,
+        throttle: bool,
+    ) {
+        self.fault = Some(fault || self.fault.unwrap_or(false));
+        self.error = Some(error || self.error.unwrap_or(false));
+        self.throttle = Some(throttle || self.throttle.unwrap_or(false));
+        self.cause
+            .get_or_insert_with(|| Cause {
+                exceptions: Vec::new(),
+            })
+            .exceptions
+            .push(exception);
+    }
+
+    /// Merge parsed `http.request`/`http.response` fields into this
+    /// segment, additionally setting `error`/`fault` for a 4xx/5xx
+    /// response status.
+    pub(crate) fn record_http(&mut self, http: Http) {
+        if let Some(status) = http.response.as_ref().and_then(|response| response.status) {
+            if (400..500).contains(&status) {
+                self.error = Some(true);
+            } else if (500..600).contains(&status) {
+                self.fault = Some(true);
+            }
+        }
+
+        if http.request.is_none() && http.response.is_none() {
+            return;
+        }
+        let current = self.http.get_or_insert_with(Http::default);
+        if http.request.is_some() {
+            current.request = http.request;
+        }
+        if http.response.is_some() {
+            current.response = http.response;
+        }
+    }
+
+    /// Merge parsed `sql.*` fields into this segment.
+    pub(crate) fn record_sql(&mut self, sql: Sql) {
+        if sql.is_empty() {
+            return;
+        }
+        self.sql.get_or_insert_with(Sql::default).merge(sql);
+    }
+
+    /// Subsegments that represent a database call (or any outbound HTTP
+    /// request) are rendered as a `"remote"` node in the X-Ray service
+    /// map, rather than opaque metadata. `namespace` is a subsegment-only
+    /// field, so this is a no-op for a root segment (which may carry its
+    /// own `http.request.url` for an *inbound* request).
+    pub(crate) fn update_namespace(&mut self) {
+        if !matches!(self.kind, Kind::Subsegment) {
+            return;
+        }
+        let is_remote = self.sql.is_some()
+            || self
+                .http
+                .as_ref()
+                .and_then(|http| http.request.as_ref())
+                .and_then(|request| request.url.as_ref())
+                .is_some();
+        if is_remote {
+            self.namespace = Some("remote".to_owned());
+        }
+    }
+}
+
+/// The `http` block of a [`Segment`]: the request/response fields X-Ray
+/// uses to drive latency-by-route and error-rate views.
+#[derive(Serialize, Default)]
+pub(crate) struct Http {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) request: Option<HttpRequest>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) response: Option<HttpResponse>,
+}
+
+#[derive(Serialize, Default)]
+pub(crate) struct HttpRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) client_ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) user_agent: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+pub(crate) struct HttpResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) content_length: Option<u64>,
+}
+
+impl Http {
+    /// Route a `request.FIELD`/`response.FIELD` key (with the
+    /// [`crate::HTTP_PREFIX`] already stripped) to the matching field of
+    /// this `http` block. Unrecognized sub-keys are ignored.
+    fn set(&mut self, field: &str, value: serde_json::Value) {
+        let (section, key) = match field.split_once('.') {
+            Some(parts) => parts,
+            None => return,
+        };
+        match section {
+            "request" => {
+                let request = self.request.get_or_insert_with(HttpRequest::default);
+                match key {
+                    "method" => request.method = as_string(value),
+                    "url" => request.url = as_string(value),
+                    "client_ip" => request.client_ip = as_string(value),
+                    "user_agent" => request.user_agent = as_string(value),
+                    _ => {}
+                }
+            }
+            "response" => {
+                let response = self.response.get_or_insert_with(HttpResponse::default);
+                match key {
+                    "status" => {
+                        response.status = value.as_u64().and_then(|n| u16::try_from(n).ok())
+                    }
+                    "content_length" => response.content_length = value.as_u64(),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Coerce a JSON value recorded by a `str` tracing field back into a
+/// `String`.
+fn as_string(value: serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s),
+        _ => None,
+    }
+}
+
+/// The `sql` block of a [`Segment`]: the fields X-Ray uses to render a
+/// subsegment as a database query in the console's SQL insights.
+#[derive(Serialize, Default)]
+pub(crate) struct Sql {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) sanitized_query: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) database_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) database_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) driver_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) preparation: Option<String>,
+}
+
+impl Sql {
+    /// Route an `sql.FIELD` key (with the [`crate::SQL_PREFIX`] already
+    /// stripped) to the matching field of this `sql` block. Unrecognized
+    /// sub-keys are ignored.
+    fn set(&mut self, field: &str, value: serde_json::Value) {
+        match field {
+            "url" => self.url = as_string(value),
+            "sanitized_query" => self.sanitized_query = as_string(value),
+            "database_type" => self.database_type = as_string(value),
+            "database_version" => self.database_version = as_string(value),
+            "driver_version" => self.driver_version = as_string(value),
+            "user" => self.user = as_string(value),
+            "preparation" => self.preparation = as_string(value),
+            _ => {}
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        let Self {
+            url,
+            sanitized_query,
+            database_type,
+            database_version,
+            driver_version,
+            user,
+            preparation,
+        } = self;
+        url.is_none()
+            && sanitized_query.is_none()
+            && database_type.is_none()
+            && database_version.is_none()
+            && driver_version.is_none()
+            && user.is_none()
+            && preparation.is_none()
+    }
+
+    fn merge(&mut self, other: Self) {
+        if other.url.is_some() {
+            self.url = other.url;
+        }
+        if other.sanitized_query.is_some() {
+            self.sanitized_query = other.sanitized_query;
+        }
+        if other.database_type.is_some() {
+            self.database_type = other.database_type;
+        }
+        if other.database_version.is_some() {
+            self.database_version = other.database_version;
+        }
+        if other.driver_version.is_some() {
+            self.driver_version = other.driver_version;
+        }
+        if other.user.is_some() {
+            self.user = other.user;
+        }
+        if other.preparation.is_some() {
+            self.preparation = other.preparation;
+        }
+    }
+}
+
+/// The `cause` of a faulting/erroring [`Segment`]: the set of exceptions
+/// that were raised while it was open.
+#[derive(Serialize, Default)]
+pub(crate) struct Cause {
+    pub(crate) exceptions: Vec<Exception>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Exception {
+    pub(crate) id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) message: Option<String>,
+    #[serde(rename = "type")]
+    pub(crate) kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stack: Option<Vec<StackFrame>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct StackFrame {
+    pub(crate) path: String,
+    pub(crate) line: u32,
+    pub(crate) label: String,
+}
+
+/// Generate a random 16-hex-character exception id, in the style used
+/// throughout X-Ray's segment document schema.
+pub(crate) fn new_exception_id() -> String {
+    use rand::prelude::*;
+    format!("{:016x}", rand::thread_rng().gen::<u64>())
 }
 
 #[derive(Serialize, Default)]
@@ -66,12 +342,24 @@ impl Annotations {
     }
 }
 
-pub(crate) fn metadata_and_annotations_from(record: &Record<'_>) -> (Metadata, Annotations) {
+/// The result of routing a span's recorded fields by their
+/// [`crate::TRACE_ID_FIELD`]/[`crate::ANNOTATION_PREFIX`]/[`crate::HTTP_PREFIX`]/
+/// [`crate::SQL_PREFIX`] naming conventions, as returned by [`fields_from`].
+pub(crate) struct ParsedFields {
+    pub(crate) metadata: Metadata,
+    pub(crate) annotations: Annotations,
+    pub(crate) http: Http,
+    pub(crate) sql: Sql,
+}
+
+pub(crate) fn fields_from(record: &Record<'_>) -> ParsedFields {
     use serde_json::Value::Object;
     let json = serde_json::to_value(record.as_serde()).expect("impossible, right?");
 
     let mut annotations = serde_json::Map::new();
     let mut metadata = serde_json::Map::new();
+    let mut http = Http::default();
+    let mut sql = Sql::default();
 
     if let Object(map) = json {
         for (field, value) in map {
@@ -82,19 +370,28 @@ pub(crate) fn metadata_and_annotations_from(record: &Record<'_>) -> (Metadata, A
             } else if let Some(("", field)) = field.split_once(crate::ANNOTATION_PREFIX) {
                 // `key` is an annotation
                 annotations.insert(field.to_owned(), value);
+            } else if let Some(("", field)) = field.split_once(crate::HTTP_PREFIX) {
+                // `key` is part of the segment's `http` block
+                http.set(field, value);
+            } else if let Some(("", field)) = field.split_once(crate::SQL_PREFIX) {
+                // `key` is part of the segment's `sql` block
+                sql.set(field, value);
             } else {
                 // `key` is metadata
                 metadata.insert(field, value);
             }
         }
     }
-    let metadata = Metadata {
-        fields: Fields(Object(metadata)),
-    };
-    let annotations = Annotations {
-        fields: Fields(Object(annotations)),
-    };
-    (metadata, annotations)
+    ParsedFields {
+        metadata: Metadata {
+            fields: Fields(Object(metadata)),
+        },
+        annotations: Annotations {
+            fields: Fields(Object(annotations)),
+        },
+        http,
+        sql,
+    }
 }
 
 #[derive(Serialize, Default)]
@@ -169,6 +466,13 @@ where
 #[derive(Serialize)]
 pub(crate) struct Id(#[serde(serialize_with = "serialize_id")] pub(crate) tracing_core::span::Id);
 
+impl Id {
+    /// Render this id as the 16-hex-character string X-Ray uses on the wire.
+    pub(crate) fn as_hex(&self) -> String {
+        format!("{:016x}", self.0.clone().into_u64())
+    }
+}
+
 fn serialize_id<S>(id: &tracing_core::span::Id, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -0,0 +1,114 @@
+//! A local implementation of [AWS X-Ray's sampling rule semantics].
+//!
+//! [AWS X-Ray's sampling rule semantics]: https://docs.aws.amazon.com/xray/latest/devguide/xray-console-sampling.html
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A local sampling rule: guarantee `reservoir` traces per wall-clock
+/// second, then sample the remainder with probability `fixed_rate`.
+pub struct Rule {
+    /// The number of traces to sample per second, guaranteed, before
+    /// `fixed_rate` is consulted.
+    pub reservoir: u32,
+    /// The probability, in `[0.0, 1.0]`, with which to sample a trace once
+    /// the `reservoir` for the current second is exhausted.
+    pub fixed_rate: f64,
+}
+
+impl Default for Rule {
+    /// X-Ray's default sampling rule: one trace per second, guaranteed,
+    /// plus 5% of any additional traces.
+    fn default() -> Self {
+        Self {
+            reservoir: 1,
+            fixed_rate: 0.05,
+        }
+    }
+}
+
+/// Makes keep/drop decisions for new root traces, honoring a [`Rule`]'s
+/// reservoir and fixed-rate.
+pub struct Sampler {
+    rule: Rule,
+    state: Mutex<State>,
+}
+
+struct State {
+    second: u64,
+    used: u32,
+}
+
+impl Sampler {
+    /// Construct a [`Sampler`] that enforces the given [`Rule`].
+    pub fn new(rule: Rule) -> Self {
+        Self {
+            rule,
+            state: Mutex::new(State { second: 0, used: 0 }),
+        }
+    }
+
+    /// Decide whether to sample a new root trace.
+    pub(crate) fn sample(&self) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let mut state = self.state.lock().unwrap();
+        if state.second != now {
+            state.second = now;
+            state.used = 0;
+        }
+
+        if state.used < self.rule.reservoir {
+            state.used += 1;
+            return true;
+        }
+        drop(state);
+
+        use rand::prelude::*;
+        rand::thread_rng().gen_bool(self.rule.fixed_rate.clamp(0.0, 1.0))
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::new(Rule::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reservoir_is_guaranteed() {
+        let sampler = Sampler::new(Rule {
+            reservoir: 3,
+            fixed_rate: 0.0,
+        });
+        // the first `reservoir` calls within a second are always sampled,
+        // regardless of `fixed_rate`
+        assert!(sampler.sample());
+        assert!(sampler.sample());
+        assert!(sampler.sample());
+    }
+
+    #[test]
+    fn fixed_rate_zero_drops_once_reservoir_is_exhausted() {
+        let sampler = Sampler::new(Rule {
+            reservoir: 0,
+            fixed_rate: 0.0,
+        });
+        assert!(!sampler.sample());
+    }
+
+    #[test]
+    fn fixed_rate_one_keeps_once_reservoir_is_exhausted() {
+        let sampler = Sampler::new(Rule {
+            reservoir: 0,
+            fixed_rate: 1.0,
+        });
+        assert!(sampler.sample());
+    }
+}
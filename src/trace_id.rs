@@ -1,8 +1,19 @@
 //! Utilities for generating/parsing AWS X-Ray `trace_id`s.
+use http::header::{HeaderName, HeaderValue};
 use tracing_core::span::Attributes;
 use tracing_core::subscriber::Subscriber;
 use tracing_subscriber::registry::{LookupSpan, SpanRef};
 
+/// The name of the [AWS X-Ray tracing header].
+///
+/// [AWS X-Ray tracing header]: https://docs.aws.amazon.com/xray/latest/devguide/xray-concepts.html#xray-concepts-tracingheader
+const AWS_XRAY_HEADER: &str = "X-Amzn-Trace-Id";
+
+/// A `trace_id`, recorded in a span's extensions so that descendant spans
+/// can find it without re-parsing headers.
+#[derive(Clone)]
+struct TraceId(String);
+
 /// Generate a fresh X-Ray trace id.
 pub fn new() -> String {
     use rand::prelude::*;
@@ -29,7 +40,7 @@ pub enum SamplingDecision {
 }
 
 impl SamplingDecision {
-    fn from_str(s: &str) -> Self {
+    pub(crate) fn from_str(s: &str) -> Self {
         match s {
             "1" => Self::Sampled,
             "0" => Self::NotSampled,
@@ -37,6 +48,17 @@ impl SamplingDecision {
             _ => Self::Unknown,
         }
     }
+
+    /// Render this decision the way X-Ray expects it on the wire. An
+    /// [`Unknown`][Self::Unknown] decision (one we can't vouch for) is
+    /// rendered as `Requested`, so a downstream service makes its own call.
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sampled => "1",
+            Self::NotSampled => "0",
+            Self::Requested | Self::Unknown => "?",
+        }
+    }
 }
 
 /// The result of [`from_headers`].
@@ -50,7 +72,6 @@ pub struct FromHeaders {
 ///
 /// [AWS X-Ray tracing header]: https://docs.aws.amazon.com/xray/latest/devguide/xray-concepts.html#xray-concepts-tracingheader
 pub fn from_headers(headers: &http::header::HeaderMap) -> Option<FromHeaders> {
-    const AWS_XRAY_HEADER: &str = "X-Amzn-Trace-Id";
     const ROOT_KEY: &str = "Root";
     const PARENT_KEY: &str = "Parent";
     const SAMPLED_KEY: &str = "Sampled";
@@ -91,8 +112,6 @@ pub fn from_span<'a, S>(span: &SpanRef<'a, S>, attr: &Attributes<'_>) -> Option<
 where
     S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
 {
-    #[derive(Clone)]
-    pub struct TraceId(String);
     let mut visitor = crate::TraceIdVisitor { trace_id: None };
     attr.record(&mut visitor);
 
@@ -113,3 +132,73 @@ where
         trace_id
     }
 }
+
+/// Build an outbound [AWS X-Ray tracing header], of the form
+/// `Root=<root>;Parent=<parent>;Sampled=<sampled>`, for attaching to a
+/// request sent to a downstream service.
+///
+/// `parent` should be the 16-hex-character id of the current segment or
+/// subsegment, so the downstream service's segment links back to it.
+///
+/// [AWS X-Ray tracing header]: https://docs.aws.amazon.com/xray/latest/devguide/xray-concepts.html#xray-concepts-tracingheader
+pub fn to_header(
+    root: &str,
+    parent: Option<&str>,
+    sampled: &SamplingDecision,
+) -> (HeaderName, HeaderValue) {
+    let mut header = format!("Root={root}");
+    if let Some(parent) = parent {
+        header.push_str(&format!(";Parent={parent}"));
+    }
+    header.push_str(&format!(";Sampled={}", sampled.as_str()));
+
+    (
+        HeaderName::from_static("x-amzn-trace-id"),
+        HeaderValue::from_str(&header)
+            .expect("trace id and span id are always valid header characters"),
+    )
+}
+
+/// Attach an outbound [AWS X-Ray tracing header][to_header] to `headers`,
+/// propagating the trace of `span` to a downstream service.
+///
+/// Does nothing if `span` isn't part of an X-Ray trace (i.e. no ancestor
+/// span carries a [`trace_id`][TRACE_ID_FIELD]).
+///
+/// [TRACE_ID_FIELD]: crate::TRACE_ID_FIELD
+pub fn inject<S>(headers: &mut http::header::HeaderMap, span: &SpanRef<'_, S>)
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+{
+    let root = match span
+        .scope()
+        .find_map(|span| span.extensions().get::<TraceId>().cloned())
+    {
+        Some(trace_id) => trace_id.0,
+        None => return,
+    };
+
+    let parent = span.scope().find_map(|span| {
+        span.extensions()
+            .get::<crate::model::Segment>()
+            .map(|s| s.id.as_hex())
+    });
+
+    let sampled = span
+        .scope()
+        .find_map(|span| {
+            span.extensions()
+                .get::<crate::Sampled>()
+                .map(|sampled| sampled.0)
+        })
+        .map_or(SamplingDecision::Sampled, |sampled| {
+            if sampled {
+                SamplingDecision::Sampled
+            } else {
+                SamplingDecision::NotSampled
+            }
+        });
+
+    let (name, value) = to_header(&root, parent.as_deref(), &sampled);
+    headers.insert(name, value);
+}
@@ -0,0 +1,131 @@
+//! The two ways a [`crate::Layer`] can ship completed segment documents:
+//! to a local [X-Ray daemon], or straight to the X-Ray service via
+//! [`PutTraceSegments`].
+//!
+//! [X-Ray daemon]: https://docs.aws.amazon.com/xray/latest/devguide/xray-daemon.html
+//! [`PutTraceSegments`]: https://docs.aws.amazon.com/xray/latest/API_PutTraceSegments.html
+use crate::model::Segment;
+use crate::xray_daemon;
+use std::io;
+
+/// The maximum size, in bytes, of the JSON documents sent in a single
+/// `PutTraceSegments` call. A batch of documents is chunked to respect
+/// this, the same way UDP datagrams to the daemon are limited to ~64KB.
+const MAX_BATCH_BYTES: usize = 64 * 1024;
+
+/// Where a [`Layer`][crate::Layer] ships its completed segment documents.
+pub(crate) enum Transport {
+    /// One UDP datagram per segment document, to a local X-Ray daemon.
+    Daemon(xray_daemon::DaemonClient<xray_daemon::Connected>),
+    /// Batched `PutTraceSegments` calls, straight to the X-Ray service.
+    Api(ApiClient),
+}
+
+impl Transport {
+    pub(crate) async fn send(&self, segments: &[Segment]) -> io::Result<()> {
+        match self {
+            Self::Daemon(client) => {
+                for segment in segments {
+                    let message = serde_json::to_vec(segment).unwrap();
+                    client.send(&message[..]).await?;
+                }
+                Ok(())
+            }
+            Self::Api(client) => client.send(segments).await,
+        }
+    }
+}
+
+/// Ships segment documents straight to the X-Ray service via
+/// [`PutTraceSegments`], for environments (Lambda-free containers, CI,
+/// restricted networks) where no daemon is reachable.
+///
+/// [`PutTraceSegments`]: https://docs.aws.amazon.com/xray/latest/API_PutTraceSegments.html
+pub(crate) struct ApiClient {
+    client: aws_sdk_xray::Client,
+}
+
+impl ApiClient {
+    /// Construct a client from the ambient AWS config (credentials,
+    /// region, etc., resolved the usual SDK way).
+    pub(crate) async fn connect() -> Self {
+        let config = aws_config::load_from_env().await;
+        Self {
+            client: aws_sdk_xray::Client::new(&config),
+        }
+    }
+
+    async fn send(&self, segments: &[Segment]) -> io::Result<()> {
+        let documents: Vec<String> = segments
+            .iter()
+            .map(|segment| serde_json::to_string(segment).unwrap())
+            .collect();
+
+        for chunk in chunk_by_size(&documents, MAX_BATCH_BYTES) {
+            self.client
+                .put_trace_segments()
+                .set_trace_segment_documents(Some(chunk.to_vec()))
+                .send()
+                .await
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        }
+        Ok(())
+    }
+}
+
+/// Split `documents` into chunks whose total serialized size doesn't
+/// exceed `max_bytes`, so a `PutTraceSegments` call never hits the API's
+/// batch-size limit the way an oversized UDP datagram silently truncates.
+fn chunk_by_size(documents: &[String], max_bytes: usize) -> Vec<&[String]> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut size = 0;
+    for (i, document) in documents.iter().enumerate() {
+        if i > start && size + document.len() > max_bytes {
+            chunks.push(&documents[start..i]);
+            start = i;
+            size = 0;
+        }
+        size += document.len();
+    }
+    if start < documents.len() {
+        chunks.push(&documents[start..]);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_in_one_chunk() {
+        let documents = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        let chunks = chunk_by_size(&documents, 64 * 1024);
+        assert_eq!(chunks, vec![&documents[..]]);
+    }
+
+    #[test]
+    fn splits_when_over_the_limit() {
+        let documents = vec!["aaaa".to_owned(), "bbbb".to_owned(), "cccc".to_owned()];
+        let chunks = chunk_by_size(&documents, 6);
+        assert_eq!(
+            chunks,
+            vec![&documents[0..1], &documents[1..2], &documents[2..3]]
+        );
+    }
+
+    #[test]
+    fn a_single_oversized_document_still_gets_its_own_chunk() {
+        let documents = vec!["a".repeat(100)];
+        let chunks = chunk_by_size(&documents, 10);
+        assert_eq!(chunks, vec![&documents[..]]);
+    }
+
+    #[test]
+    fn empty_input_yields_no_chunks() {
+        let documents: Vec<String> = Vec::new();
+        let chunks = chunk_by_size(&documents, 64 * 1024);
+        assert!(chunks.is_empty());
+    }
+}
@@ -4,12 +4,16 @@ use tokio::net::UdpSocket;
 const DAEMON_HEADER: &[u8] = b"{\"format\": \"json\", \"version\": 1}\n";
 const DEFAULT_UDP_REMOTE_PORT: u16 = 2000;
 
+/// The environment variable used to configure the X-Ray daemon's address,
+/// in place of the default `127.0.0.1:2000`.
+const AWS_XRAY_DAEMON_ADDRESS: &str = "AWS_XRAY_DAEMON_ADDRESS";
+
 pub(crate) struct DaemonClient<S: ClientState> {
     state: S,
 }
 
 pub struct Start {
-    remote_port: u16,
+    remote_addr: String,
 }
 
 pub struct Connected {
@@ -21,17 +25,18 @@ impl ClientState for Start {}
 impl ClientState for Connected {}
 
 impl DaemonClient<Start> {
-    pub(crate) fn new(remote_port: u16) -> Self {
+    pub(crate) fn new(remote_addr: impl Into<String>) -> Self {
         DaemonClient {
-            state: Start { remote_port },
+            state: Start {
+                remote_addr: remote_addr.into(),
+            },
         }
     }
 
     pub(crate) async fn connect(&self) -> io::Result<DaemonClient<Connected>> {
         // Let the OS choose an IP and port for us...
         let sock = UdpSocket::bind("0.0.0.0:0").await?;
-        let remote_addr = format!("127.0.0.1:{}", self.state.remote_port);
-        sock.connect(remote_addr).await?;
+        sock.connect(&self.state.remote_addr).await?;
         Ok(DaemonClient {
             state: Connected { sock },
         })
@@ -39,8 +44,14 @@ impl DaemonClient<Start> {
 }
 
 impl Default for DaemonClient<Start> {
+    /// Reads the daemon's address from [`AWS_XRAY_DAEMON_ADDRESS`], falling
+    /// back to `127.0.0.1:2000` if it's unset.
+    ///
+    /// [`AWS_XRAY_DAEMON_ADDRESS`]: https://docs.aws.amazon.com/xray/latest/devguide/xray-daemon.html
     fn default() -> Self {
-        DaemonClient::new(DEFAULT_UDP_REMOTE_PORT)
+        let remote_addr = std::env::var(AWS_XRAY_DAEMON_ADDRESS)
+            .unwrap_or_else(|_| format!("127.0.0.1:{DEFAULT_UDP_REMOTE_PORT}"));
+        DaemonClient::new(remote_addr)
     }
 }
 